@@ -0,0 +1,278 @@
+use crate::widget_base::MessageReader;
+
+use dces::entity::Entity;
+
+/// Severity of a [`Notification`]. The message bar renders every level with
+/// the same `message-bar` style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message shown by the built-in `MessageBar` widget, e.g. a compile,
+/// config or load error an app wants to surface without overwriting its own
+/// UI content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub level: Level,
+    pub text: String,
+}
+
+impl Notification {
+    /// Creates a new notification.
+    pub fn new(level: Level, text: impl Into<String>) -> Self {
+        Notification {
+            level,
+            text: text.into(),
+        }
+    }
+}
+
+/// Sent to a `MessageBar`'s entity to dismiss the pending notification at
+/// `index`, e.g. in response to a click on its rendered `[X]` close region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DismissNotification(pub usize);
+
+/// State for the built-in `MessageBar` widget. Collects [`Notification`]s
+/// sent to its entity via `MessageAdapter`, de-duplicating identical pending
+/// notifications so repeated errors collapse into a single stacked line.
+/// Dismissing a notification (e.g. by clicking its `[X]` close region)
+/// shrinks the reserved area back down; receiving a long or additional
+/// notification grows it so messages wrap instead of being truncated.
+#[derive(Debug, Default)]
+pub struct MessageBarState {
+    entity: Entity,
+    pending: Vec<Notification>,
+}
+
+impl MessageBarState {
+    /// Creates message bar state for `entity`.
+    pub fn new(entity: Entity) -> Self {
+        MessageBarState {
+            entity,
+            pending: vec![],
+        }
+    }
+
+    /// Returns the entity this state collects notifications for.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// Reads pending `Notification` and `DismissNotification` messages from
+    /// `messages`: new notifications are appended unless already pending,
+    /// and dismiss requests remove the notification at their index.
+    ///
+    /// Dismiss indices are applied in descending order so that dismissing
+    /// more than one notification in the same batch (e.g. two `[X]` clicks
+    /// landing in the same poll) can't have an earlier removal shift the
+    /// list out from under a later one.
+    pub fn receive_messages(&mut self, mut messages: MessageReader) {
+        for notification in messages.read::<Notification>() {
+            if !self.pending.contains(&notification) {
+                self.pending.push(notification);
+            }
+        }
+
+        let mut dismiss_indices: Vec<usize> = messages
+            .read::<DismissNotification>()
+            .map(|DismissNotification(index)| index)
+            .collect();
+        dismiss_indices.sort_unstable_by(|a, b| b.cmp(a));
+        dismiss_indices.dedup();
+
+        for index in dismiss_indices {
+            self.dismiss(index);
+        }
+    }
+
+    /// Returns the notifications currently shown, in the order they should
+    /// be stacked (oldest first).
+    pub fn pending(&self) -> &[Notification] {
+        &self.pending
+    }
+
+    /// Dismisses the notification at `index`, e.g. in response to its
+    /// `[X]` close region being clicked.
+    pub fn dismiss(&mut self, index: usize) {
+        if index < self.pending.len() {
+            self.pending.remove(index);
+        }
+    }
+
+    /// Returns the height the message bar needs to reserve at the bottom of
+    /// the window to show every pending notification without truncating it,
+    /// wrapping lines wider than `max_width` over multiple lines of
+    /// `line_height`.
+    pub fn reserved_height(&self, max_width: u32, line_height: u32) -> u32 {
+        if max_width == 0 {
+            return 0;
+        }
+
+        let wrapped_lines: usize = self
+            .pending
+            .iter()
+            .map(|notification| wrap_notification_text(&notification.text, max_width, line_height).len())
+            .sum();
+
+        wrapped_lines as u32 * line_height
+    }
+}
+
+/// Approximates how many characters fit on one `max_width`-wide line of text
+/// rendered at `line_height`, using a half-`line_height` average glyph width
+/// since this is used for reserving layout space before any glyph has
+/// actually been shaped.
+fn chars_per_line(max_width: u32, line_height: u32) -> usize {
+    (max_width / (line_height / 2).max(1)).max(1) as usize
+}
+
+/// Wraps `text` into the lines a `max_width`-wide, `line_height`-tall message
+/// bar would need to show it without truncating it. Shared by
+/// `reserved_height` (which only needs the line count) and the backend,
+/// which renders each returned line.
+pub fn wrap_notification_text(text: &str, max_width: u32, line_height: u32) -> Vec<String> {
+    let chars_per_line = chars_per_line(max_width, line_height);
+    let chars: Vec<char> = text.chars().collect();
+
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+
+    chars
+        .chunks(chars_per_line)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widget_base::message_adapter::MessageBox;
+    use std::any::TypeId;
+    use std::collections::HashMap;
+
+    fn reader(entity: Entity, messages: Vec<MessageBox>) -> MessageReader {
+        let mut by_type: HashMap<TypeId, Vec<MessageBox>> = HashMap::new();
+        for message in messages {
+            by_type
+                .entry(message.message_type())
+                .or_insert_with(Vec::new)
+                .push(message);
+        }
+        MessageReader::new(by_type, entity)
+    }
+
+    #[test]
+    fn receiving_the_same_notification_twice_does_not_duplicate_it() {
+        let entity = Entity(0);
+        let mut state = MessageBarState::new(entity);
+
+        state.receive_messages(reader(
+            entity,
+            vec![MessageBox::new(
+                Notification::new(Level::Error, "disk full"),
+                entity,
+            )],
+        ));
+        state.receive_messages(reader(
+            entity,
+            vec![MessageBox::new(
+                Notification::new(Level::Error, "disk full"),
+                entity,
+            )],
+        ));
+
+        assert_eq!(state.pending().len(), 1);
+    }
+
+    #[test]
+    fn dismiss_message_removes_the_notification_at_its_index() {
+        let entity = Entity(0);
+        let mut state = MessageBarState::new(entity);
+
+        state.receive_messages(reader(
+            entity,
+            vec![
+                MessageBox::new(Notification::new(Level::Info, "saved"), entity),
+                MessageBox::new(Notification::new(Level::Warning, "low battery"), entity),
+            ],
+        ));
+        state.receive_messages(reader(entity, vec![MessageBox::new(DismissNotification(0), entity)]));
+
+        assert_eq!(state.pending(), &[Notification::new(Level::Warning, "low battery")]);
+    }
+
+    #[test]
+    fn dismissing_two_notifications_in_the_same_batch_removes_both() {
+        let entity = Entity(0);
+        let mut state = MessageBarState::new(entity);
+
+        state.receive_messages(reader(
+            entity,
+            vec![
+                MessageBox::new(Notification::new(Level::Info, "a"), entity),
+                MessageBox::new(Notification::new(Level::Info, "b"), entity),
+                MessageBox::new(Notification::new(Level::Info, "c"), entity),
+            ],
+        ));
+        // Dismiss indices 0 and 1 in a single batch, as could happen if two
+        // close-region clicks land in the same poll. A naive in-order apply
+        // would remove "a" first, shift "c" into index 1, and wrongly
+        // remove "c" instead of "b".
+        state.receive_messages(reader(
+            entity,
+            vec![
+                MessageBox::new(DismissNotification(0), entity),
+                MessageBox::new(DismissNotification(1), entity),
+            ],
+        ));
+
+        assert_eq!(state.pending(), &[Notification::new(Level::Info, "c")]);
+    }
+
+    #[test]
+    fn reserved_height_is_zero_without_pending_notifications() {
+        let state = MessageBarState::new(Entity(0));
+
+        assert_eq!(state.reserved_height(200, 16), 0);
+    }
+
+    #[test]
+    fn reserved_height_wraps_long_notifications_over_multiple_lines() {
+        let mut state = MessageBarState::new(Entity(0));
+        state.pending.push(Notification::new(
+            Level::Error,
+            "a notification long enough to wrap across more than one line",
+        ));
+
+        let height = state.reserved_height(80, 16);
+
+        assert!(height > 16, "expected a wrapped notification to reserve more than one line");
+        assert_eq!(height % 16, 0);
+    }
+
+    #[test]
+    fn wrap_notification_text_splits_across_as_many_lines_as_reserved_height_counts() {
+        let text = "a notification long enough to wrap across more than one line";
+        let lines = wrap_notification_text(text, 80, 16);
+
+        assert!(lines.len() > 1);
+        assert_eq!(lines.concat().chars().count(), text.chars().count());
+
+        let mut state = MessageBarState::new(Entity(0));
+        state.pending.push(Notification::new(Level::Error, text));
+        assert_eq!(state.reserved_height(80, 16), lines.len() as u32 * 16);
+    }
+
+    #[test]
+    fn reserved_height_sums_every_pending_notification() {
+        let mut state = MessageBarState::new(Entity(0));
+        state.pending.push(Notification::new(Level::Info, "a"));
+        state.pending.push(Notification::new(Level::Info, "b"));
+
+        assert_eq!(state.reserved_height(200, 16), 2 * 16);
+    }
+}
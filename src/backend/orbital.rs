@@ -1,19 +1,394 @@
-// use orbfont;
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::mpsc;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use orbclient::{Color, Window as OrbWindow};
-use orbclient::{Mode, Renderer as OrbRenderer};
+use orbclient::{Event as OrbEvent, Mode, Renderer as OrbRenderer};
+use orbfont;
+
+use orbtk_core::widget_base::message_adapter::MessageAdapter;
+use orbtk_core::widget_base::message_bar::{wrap_notification_text, DismissNotification, MessageBarState};
 
 use {Backend, Rect, RenderContext, Renderer, Selector, Theme, EventManager, MouseEvent, SystemEvent, MouseButton};
 
+/// Identifies a cached glyph: the font family it was shaped with, the
+/// character, its pixel size and its fill color. Re-shaping the same glyph
+/// every frame is what made text rendering via `char` slow and kerning-less,
+/// so every glyph that has already been rasterized is kept around under this
+/// key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_id: String,
+    c: char,
+    size: u32,
+    color: u32,
+}
+
+/// A single pre-rasterized glyph, along with the advance the pen should move
+/// by after drawing it.
+struct CachedGlyph {
+    text: orbfont::Text,
+    advance: u32,
+    height: u32,
+}
+
+/// A ready-to-blit command buffer: the result of the UI thread diffing and
+/// rasterizing the widget tree for one frame. The paint thread owns the real
+/// `OrbWindow` surface, so rasterization happens into this plain pixel
+/// buffer instead, and only the finished buffer crosses the thread boundary.
+#[derive(Clone)]
+pub struct SceneBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    mode: Cell<Mode>,
+}
+
+impl SceneBuffer {
+    /// Creates a scene buffer of the given size, cleared to transparent.
+    pub fn new(width: u32, height: u32) -> Self {
+        SceneBuffer {
+            width,
+            height,
+            pixels: vec![Color::rgba(0, 0, 0, 0); (width * height) as usize],
+            mode: Cell::new(Mode::Blend),
+        }
+    }
+
+    /// Resizes the buffer in place, clearing it to transparent.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![Color::rgba(0, 0, 0, 0); (width * height) as usize];
+    }
+}
+
+impl OrbRenderer for SceneBuffer {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn data(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    fn data_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    fn sync(&mut self) -> bool {
+        true
+    }
+
+    fn mode(&self) -> &Cell<Mode> {
+        &self.mode
+    }
+}
+
+/// A command sent from the UI thread to the dedicated paint thread that owns
+/// the `OrbWindow` surface, following Servo's canvas-task model: the UI
+/// thread only diffs the widget tree and ships a finished buffer; the paint
+/// thread is the only one that ever blits to the real surface.
+pub enum PaintMsg {
+    /// The window surface moved to `(x, y)` and was resized to the given
+    /// width and height.
+    Resize(i32, i32, u32, u32),
+    /// Blit `SceneBuffer` to the surface and signal the attached sender once
+    /// done, so a held [`FrameHandle`] can unblock.
+    RenderFrame(SceneBuffer, mpsc::Sender<()>),
+    /// Flush the surface without blitting a new frame.
+    Sync,
+    /// Stop the paint thread and drop the surface.
+    Quit,
+}
+
+/// A handle returned by [`OrbitalBackend::request_frame`] that the paint
+/// thread signals once the requested frame has been blitted, so callers can
+/// throttle how many frames they have in flight.
+pub struct FrameHandle {
+    done: mpsc::Receiver<()>,
+}
+
+impl FrameHandle {
+    /// Blocks until the paint thread has finished blitting the requested
+    /// frame.
+    pub fn wait(self) {
+        let _ = self.done.recv();
+    }
+}
+
+/// Spawns the paint thread, handing it ownership of `inner`. Returns the
+/// sender used to ship `PaintMsg`s to it and the receiver the UI thread
+/// drains for input events forwarded from `inner`'s event queue.
+fn spawn_paint_thread(
+    mut inner: OrbWindow,
+) -> (mpsc::Sender<PaintMsg>, mpsc::Receiver<OrbEvent>) {
+    let (paint_sender, paint_receiver) = mpsc::channel::<PaintMsg>();
+    let (event_sender, event_receiver) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        match paint_receiver.try_recv() {
+            Ok(PaintMsg::Resize(x, y, width, height)) => {
+                inner.set_pos(x, y);
+                inner.set_size(width, height);
+            }
+            Ok(PaintMsg::RenderFrame(scene, done)) => {
+                inner.data_mut().copy_from_slice(scene.data());
+                inner.sync();
+                let _ = done.send(());
+            }
+            Ok(PaintMsg::Sync) => {
+                inner.sync();
+            }
+            Ok(PaintMsg::Quit) => break,
+            Err(mpsc::TryRecvError::Disconnected) => break,
+            Err(mpsc::TryRecvError::Empty) => {}
+        }
+
+        for event in inner.events() {
+            if event_sender.send(event).is_err() {
+                return;
+            }
+        }
+
+        thread::sleep(Duration::from_millis(4));
+    });
+
+    (paint_sender, event_receiver)
+}
+
 pub struct OrbitalBackend {
-    inner: OrbWindow,
+    scene: SceneBuffer,
+    paint_sender: mpsc::Sender<PaintMsg>,
+    event_receiver: mpsc::Receiver<OrbEvent>,
     theme: Arc<Theme>,
     mouse_buttons: (bool, bool, bool),
+    /// Font bytes registered up front via `register_font_bytes`, keyed by
+    /// family name. Preferred over `fonts` on first use of that family so
+    /// rendering does not depend on the font being discoverable through the
+    /// system font database (e.g. fontconfig), which may not be present at
+    /// all in a sandboxed or headless environment.
+    registered_font_bytes: HashMap<String, &'static [u8]>,
+    fonts: HashMap<String, orbfont::Font>,
+    glyph_cache: HashMap<GlyphKey, CachedGlyph>,
+    last_mouse_position: (i32, i32),
+    close_regions: Vec<Rect>,
+    dismiss_handler: Option<Box<dyn FnMut(usize) + Send>>,
 }
 
-impl Renderer for OrbWindow {
+impl OrbitalBackend {
+    pub fn new(theme: Arc<Theme>) -> OrbitalBackend {
+        let inner = OrbWindow::new_flags(0, 0, 0, 0, "", &[]).unwrap();
+        let (width, height) = (inner.width(), inner.height());
+        let (paint_sender, event_receiver) = spawn_paint_thread(inner);
+
+        OrbitalBackend {
+            scene: SceneBuffer::new(width, height),
+            paint_sender,
+            event_receiver,
+            theme,
+            mouse_buttons: (false, false, false),
+            registered_font_bytes: HashMap::new(),
+            fonts: HashMap::new(),
+            glyph_cache: HashMap::new(),
+            last_mouse_position: (0, 0),
+            close_regions: vec![],
+            dismiss_handler: None,
+        }
+    }
+
+    /// Registers `bytes` as the font data for `font_id`, so later lookups of
+    /// that family (e.g. via `render_text`) load directly from `bytes`
+    /// instead of resolving it through the system font database. Mirrors the
+    /// app-facing `register_fonts`/`load_font_from_bytes` flow.
+    pub fn register_font_bytes(&mut self, font_id: impl Into<String>, bytes: &'static [u8]) {
+        self.registered_font_bytes.insert(font_id.into(), bytes);
+    }
+
+    /// Ships the current scene buffer to the paint thread to be blitted,
+    /// returning a handle the caller can `wait` on to throttle how many
+    /// frames it keeps in flight.
+    pub fn request_frame(&mut self) -> FrameHandle {
+        let (done_sender, done_receiver) = mpsc::channel();
+
+        let _ = self
+            .paint_sender
+            .send(PaintMsg::RenderFrame(self.scene.clone(), done_sender));
+
+        FrameHandle {
+            done: done_receiver,
+        }
+    }
+
+    /// Registers the message bar's current `[X]` close regions, one per
+    /// stacked notification, in the same order as the notifications they
+    /// close so a hit on region `i` maps to notification `i`. Called by
+    /// the message bar widget whenever it re-lays out its notifications.
+    pub fn set_close_regions(&mut self, regions: Vec<Rect>) {
+        self.close_regions = regions;
+    }
+
+    /// Registers the callback invoked with the notification index whenever a
+    /// registered close region is clicked, e.g. to send a dismiss message to
+    /// the message bar's entity.
+    pub fn on_dismiss(&mut self, handler: impl FnMut(usize) + Send + 'static) {
+        self.dismiss_handler = Some(Box::new(handler));
+    }
+
+    /// Wires this backend's close-region clicks to `state`'s entity: a click
+    /// on a rendered `[X]` sends a `DismissNotification` for that index back
+    /// through `message_adapter`, which `MessageBarState::receive_messages`
+    /// turns into a removal from the pending list.
+    pub fn wire_message_bar(&mut self, state: &MessageBarState, message_adapter: Arc<MessageAdapter>) {
+        let entity = state.entity();
+        self.on_dismiss(move |index| {
+            message_adapter.send_message(DismissNotification(index), entity);
+        });
+    }
+
+    /// Renders `state`'s pending notifications stacked at the bottom of a
+    /// `window_width` x `window_height` window, each wrapped over as many
+    /// lines as it needs, with a trailing `[X]` close label, and registers
+    /// their close regions via
+    /// `set_close_regions` so a click is routed back by `drain_events`.
+    pub fn render_message_bar(
+        &mut self,
+        theme: &Arc<Theme>,
+        state: &MessageBarState,
+        window_width: u32,
+        window_height: u32,
+    ) {
+        if state.pending().is_empty() {
+            self.set_close_regions(vec![]);
+            return;
+        }
+
+        let selector: Selector = "message-bar".into();
+        let font_id = theme
+            .string("font-family", &selector)
+            .unwrap_or_else(|| "Roboto Regular".to_string());
+        let size = theme.uint("font-size", &selector).max(1);
+        let color = theme.color("color", &selector);
+
+        let line_height = self
+            .glyph(&font_id, ' ', size, color)
+            .map(|glyph| glyph.height)
+            .unwrap_or(size);
+        let close_label = "[X]";
+        let close_glyph_width: u32 = close_label
+            .chars()
+            .map(|c| {
+                self.glyph(&font_id, c, size, color)
+                    .map(|glyph| glyph.advance)
+                    .unwrap_or(size)
+            })
+            .sum();
+
+        // Leave room for the close label so wrapping (and reserved_height,
+        // which must agree on the same width) don't run text under it.
+        let text_width = window_width.saturating_sub(close_glyph_width);
+        let mut y = window_height as i32 - state.reserved_height(text_width, line_height) as i32;
+
+        let mut regions = Vec::with_capacity(state.pending().len());
+        let theme = theme.clone();
+
+        for notification in state.pending() {
+            let lines = wrap_notification_text(&notification.text, text_width, line_height);
+            let notification_top = y;
+
+            for line in &lines {
+                let line_bounds = Rect {
+                    x: 0,
+                    y,
+                    width: window_width,
+                    height: line_height,
+                };
+
+                self.render_rectangle(&theme, &line_bounds, &selector, (0, 0));
+                self.render_text(&theme, line, &line_bounds, &selector, (0, 0));
+
+                y += line_height as i32;
+            }
+
+            let close_bounds = Rect {
+                x: window_width as i32 - close_glyph_width as i32,
+                y: notification_top,
+                width: close_glyph_width,
+                height: lines.len() as u32 * line_height,
+            };
+            self.render_text(&theme, close_label, &close_bounds, &selector, (0, 0));
+
+            regions.push(close_bounds);
+        }
+
+        self.set_close_regions(regions);
+    }
+
+    /// Returns the font registered for `font_id` (its family name), loading
+    /// and caching it on first use. Prefers font bytes the app already
+    /// registered via `register_font_bytes` over resolving `font_id` through
+    /// the system font database, and falls back to the system's default
+    /// font rather than panicking if neither is available (e.g. because
+    /// fontconfig has no matching family in a headless or sandboxed
+    /// environment).
+    fn font(&mut self, font_id: &str) -> Option<&orbfont::Font> {
+        if !self.fonts.contains_key(font_id) {
+            let font = self
+                .registered_font_bytes
+                .get(font_id)
+                .and_then(|bytes| orbfont::Font::from_data(*bytes).ok())
+                .or_else(|| orbfont::Font::find(Some(font_id), None, false).ok())
+                .or_else(|| orbfont::Font::find(None, None, false).ok());
+
+            if let Some(font) = font {
+                self.fonts.insert(font_id.to_string(), font);
+            }
+        }
+
+        self.fonts.get(font_id)
+    }
+
+    /// Ensures the glyph for `c` is shaped and rasterized for the given font,
+    /// size and color, inserting it into the glyph cache if it isn't there
+    /// already. Returns `None` if no font could be resolved for `font_id`,
+    /// in which case the caller should skip drawing that glyph instead of
+    /// panicking.
+    fn glyph(&mut self, font_id: &str, c: char, size: u32, color: Color) -> Option<&CachedGlyph> {
+        let key = GlyphKey {
+            font_id: font_id.to_string(),
+            c,
+            size,
+            color: color.data,
+        };
+
+        if !self.glyph_cache.contains_key(&key) {
+            let font = self.font(font_id)?;
+            let mut buf = [0; 4];
+            let text = font.render(c.encode_utf8(&mut buf), size as f32);
+            let (advance, height) = text.size();
+
+            self.glyph_cache.insert(
+                key.clone(),
+                CachedGlyph {
+                    text,
+                    advance,
+                    height,
+                },
+            );
+        }
+
+        self.glyph_cache.get(&key)
+    }
+}
+
+impl Renderer for OrbitalBackend {
     fn render(&mut self, theme: &Arc<Theme>) {
         // render window background
         self.set(theme.color("background", &"window".into()));
@@ -63,109 +438,142 @@ impl Renderer for OrbWindow {
         selector: &Selector,
         offset: (i32, i32),
     ) {
-        // if let Some(font) = &self.font {
-        //     let line = font.render(text, 64.0);
-        //     line.draw(&mut self.inner, 20, 20, Color::rgb(0, 0, 0));
-        // } else {
-        let rect = Rect::new(
-            bounds.x + offset.0,
-            bounds.y + offset.1,
-            bounds.width,
-            bounds.height,
-        );
-        let mut current_rect = Rect::new(
-            bounds.x + offset.0,
-            bounds.y + offset.1,
-            bounds.width,
-            bounds.height,
-        );
-        let x = rect.x;
+        let font_id = theme
+            .string("font-family", selector)
+            .unwrap_or_else(|| "Roboto Regular".to_string());
+        let size = theme.uint("font-size", selector).max(1);
+        let color = theme.color("color", selector);
+
+        let origin_x = bounds.x + offset.0;
+        let max_x = origin_x + bounds.width as i32;
+        let max_y = bounds.y + offset.1 + bounds.height as i32;
+
+        let line_height = match self.glyph(&font_id, ' ', size, color) {
+            Some(glyph) => glyph.height as i32,
+            // No font could be resolved at all; there is nothing sensible to
+            // draw.
+            None => return,
+        };
+
+        let mut pen_x = origin_x;
+        let mut pen_y = bounds.y + offset.1;
 
         for c in text.chars() {
             if c == '\n' {
-                current_rect.x = x;
-                current_rect.y += 16;
-            } else {
-                if current_rect.x + 8 <= rect.x + rect.width as i32
-                    && current_rect.y + 16 <= rect.y + rect.height as i32
-                {
-                    self.char(
-                        current_rect.x,
-                        current_rect.y,
-                        c,
-                        theme.color("color", selector),
-                    );
-                }
-                current_rect.x += 8;
+                pen_x = origin_x;
+                pen_y += line_height;
+                continue;
+            }
+
+            if self.glyph(&font_id, c, size, color).is_none() {
+                continue;
             }
+
+            // Split the borrow so the cached glyph (borrowed from
+            // `glyph_cache`) can be drawn onto `scene` at the same time.
+            let OrbitalBackend {
+                glyph_cache, scene, ..
+            } = self;
+            let glyph = glyph_cache
+                .get(&GlyphKey {
+                    font_id: font_id.clone(),
+                    c,
+                    size,
+                    color: color.data,
+                })
+                .expect("OrbitalBackend::render_text: Glyph was just cached.");
+            let advance = glyph.advance as i32;
+
+            if pen_x + advance <= max_x && pen_y + line_height <= max_y {
+                glyph.text.draw(scene, pen_x, pen_y, color);
+            }
+
+            pen_x += advance;
         }
-        // }
     }
-}
 
-impl OrbitalBackend {
-    pub fn new(theme: Arc<Theme>) -> OrbitalBackend {
-        OrbitalBackend {
-            inner: OrbWindow::new_flags(0, 0, 0, 0, "", &[]).unwrap(),
-            theme,
-            mouse_buttons: (false, false, false),
+    fn measure_text(&mut self, theme: &Arc<Theme>, text: &str, selector: &Selector) -> (u32, u32) {
+        let font_id = theme
+            .string("font-family", selector)
+            .unwrap_or_else(|| "Roboto Regular".to_string());
+        let size = theme.uint("font-size", selector).max(1);
+        let color = theme.color("color", selector);
+
+        let line_height = match self.glyph(&font_id, ' ', size, color) {
+            Some(glyph) => glyph.height,
+            None => return (0, 0),
+        };
+
+        let mut width = 0;
+        let mut max_width = 0;
+        let mut height = line_height;
+
+        for c in text.chars() {
+            if c == '\n' {
+                max_width = max_width.max(width);
+                width = 0;
+                height += line_height;
+                continue;
+            }
+
+            if let Some(glyph) = self.glyph(&font_id, c, size, color) {
+                width += glyph.advance;
+            }
         }
+
+        (max_width.max(width), height)
     }
 }
 
 impl OrbRenderer for OrbitalBackend {
     fn width(&self) -> u32 {
-        self.inner.width()
+        self.scene.width()
     }
 
     fn height(&self) -> u32 {
-        self.inner.height()
+        self.scene.height()
     }
 
     fn data(&self) -> &[Color] {
-        self.inner.data()
+        self.scene.data()
     }
 
     fn data_mut(&mut self) -> &mut [Color] {
-        self.inner.data_mut()
+        self.scene.data_mut()
     }
 
     fn sync(&mut self) -> bool {
-        self.inner.sync()
+        self.request_frame().wait();
+        true
     }
 
     fn mode(&self) -> &Cell<Mode> {
-        &self.inner.mode()
+        self.scene.mode()
     }
 
     fn char(&mut self, x: i32, y: i32, c: char, color: Color) {
-        // if let Some(ref font) = self.font {
-        //     let mut buf = [0; 4];
-        //     font.render(&c.encode_utf8(&mut buf), 16.0)
-        //         .draw(&mut self.inner, x, y, color)
-        // } else {
-            self.inner.char(x, y, c, color);
-        // }
+        self.scene.char(x, y, c, color);
     }
 }
 
 impl Drop for OrbitalBackend {
     fn drop(&mut self) {
-        self.inner.sync();
+        let _ = self.paint_sender.send(PaintMsg::Quit);
     }
 }
 
 impl Backend for OrbitalBackend {
     fn drain_events(&mut self, event_manager: &mut EventManager) {
-        self.inner.sync();
+        let _ = self.paint_sender.send(PaintMsg::Sync);
 
-        for event in self.inner.events() {
+        while let Ok(event) = self.event_receiver.try_recv() {
             match event.to_option() {
                 orbclient::EventOption::Mouse(mouse) => {
+                    self.last_mouse_position = (mouse.x, mouse.y);
                     event_manager.register_event(MouseEvent::Move((mouse.x, mouse.y)));
                 },
                 orbclient::EventOption::Button(button) => {
-                    
+
 
                     if !button.left && !button.middle && !button.right {
 
@@ -178,6 +586,19 @@ impl Backend for OrbitalBackend {
                                 MouseButton::Right
                             }
                         };
+
+                        let (x, y) = self.last_mouse_position;
+                        if let Some(index) = self.close_regions.iter().position(|region| {
+                            x >= region.x
+                                && x < region.x + region.width as i32
+                                && y >= region.y
+                                && y < region.y + region.height as i32
+                        }) {
+                            if let Some(dismiss_handler) = &mut self.dismiss_handler {
+                                dismiss_handler(index);
+                            }
+                        }
+
                         event_manager.register_event(MouseEvent::Up(button))
                     } else {
                          let button = {
@@ -207,14 +628,21 @@ impl Backend for OrbitalBackend {
     }
 
     fn bounds(&mut self, bounds: &Rect) {
-        self.inner.set_pos(bounds.x, bounds.y);
-        self.inner.set_size(bounds.width, bounds.height);
+        self.scene.resize(bounds.width, bounds.height);
+        let _ = self.paint_sender.send(PaintMsg::Resize(
+            bounds.x,
+            bounds.y,
+            bounds.width,
+            bounds.height,
+        ));
     }
 
     fn render_context(&mut self) -> RenderContext {
+        let theme = self.theme.clone();
+
         RenderContext {
-            renderer: &mut self.inner,
-            theme: self.theme.clone(),
+            renderer: self,
+            theme,
         }
     }
 }